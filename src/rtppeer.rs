@@ -73,6 +73,128 @@ pub enum Response<'a> {
     Disconnect(DisconnectReason),
 }
 
+const MAX_MIDI_CHANNELS: usize = 16;
+
+// Recovery journal: this fork's own byte-aligned encoding for replaying commands lost to a
+// dropped UDP datagram. It takes its chapter vocabulary (P/C/W/N/T) and general shape from RFC
+// 6295 Appendix A, but every structure below (channel-journal header, chapter layout, field
+// widths) is this implementation's private format, not the RFC's bit-packed wire format. It is
+// only understood by another peer running this same code and is not expected to interoperate
+// with, or be parseable by, a standards-compliant AppleMIDI/RTP-MIDI session host. Writing is in
+// `send_midi`/`write_recovery_journal`, reading in `recover_from_journal`.
+//
+// Chapter-present bitmask bits for our channel-journal header.
+const CHAPTER_PROGRAM: u8 = 0x01; // Chapter P, last program change
+const CHAPTER_CONTROL: u8 = 0x02; // Chapter C, control changes
+const CHAPTER_PITCH_WHEEL: u8 = 0x04; // Chapter W, pitch wheel
+const CHAPTER_NOTE: u8 = 0x08; // Chapter N, note on/off log
+const CHAPTER_CHANNEL_PRESSURE: u8 = 0x10; // Chapter T, channel pressure
+
+// How many recent note on/off events we keep per channel, so the journal does not grow
+// without bound when a peer stays behind for a long time.
+const MAX_JOURNAL_NOTES: usize = 16;
+
+#[derive(Debug, Copy, Clone)]
+struct JournalNote {
+    seqnr: u16,
+    note: u8,
+    velocity: u8,
+    on: bool,
+}
+
+// Rolling per-channel recovery state (see the module note above `CHAPTER_PROGRAM` for the wire
+// format). Updated on every command we send, so it always reflects "what the receiver should
+// believe is true". The note log is pruned once the peer has acknowledged the packets it came
+// from; the other chapters hold current state and persist until overwritten.
+#[derive(Debug, Clone, Default)]
+struct ChannelJournal {
+    program: Option<u8>,
+    controllers: Vec<(u8, u8)>,
+    pitch_wheel: Option<(u8, u8)>,
+    pressure: Option<u8>,
+    notes: Vec<JournalNote>,
+}
+
+impl ChannelJournal {
+    fn chapter_mask(&self) -> u8 {
+        let mut mask = 0u8;
+        if self.program.is_some() {
+            mask |= CHAPTER_PROGRAM;
+        }
+        if !self.controllers.is_empty() {
+            mask |= CHAPTER_CONTROL;
+        }
+        if self.pitch_wheel.is_some() {
+            mask |= CHAPTER_PITCH_WHEEL;
+        }
+        if !self.notes.is_empty() {
+            mask |= CHAPTER_NOTE;
+        }
+        if self.pressure.is_some() {
+            mask |= CHAPTER_CHANNEL_PRESSURE;
+        }
+        mask
+    }
+
+    fn is_empty(&self) -> bool {
+        self.chapter_mask() == 0
+    }
+
+    // How many bytes this channel's journal section would take on the wire, including its own
+    // 4-byte channel-journal header, so callers can check it fits before writing it.
+    fn encoded_len(&self) -> usize {
+        if self.is_empty() {
+            return 0;
+        }
+        let mut len = 4; // channel-journal header: channel nr, chapter mask, 2-byte length
+        if self.program.is_some() {
+            len += 1;
+        }
+        if !self.controllers.is_empty() {
+            len += 1 + self.controllers.len() * 2;
+        }
+        if self.pitch_wheel.is_some() {
+            len += 2;
+        }
+        if !self.notes.is_empty() {
+            len += 5 + self.notes.len() * 4;
+        }
+        if self.pressure.is_some() {
+            len += 1;
+        }
+        len
+    }
+
+    // Drop note events the peer has already acknowledged (by packet seqno); the other chapters
+    // (program, controllers, pitch wheel, pressure) describe the channel's *current* state, not
+    // a log, so they are not pruned here — they are simply overwritten as new commands update
+    // them.
+    fn forget_acked_notes(&mut self, sequence_ack: u16) {
+        self.notes
+            .retain(|n| (n.seqnr.wrapping_sub(sequence_ack) as i16) > 0);
+    }
+
+    fn set_controller(&mut self, controller: u8, value: u8) {
+        if let Some(entry) = self.controllers.iter_mut().find(|(c, _)| *c == controller) {
+            entry.1 = value;
+        } else {
+            self.controllers.push((controller, value));
+        }
+    }
+
+    fn push_note(&mut self, seqnr: u16, note: u8, velocity: u8, on: bool) {
+        self.notes.push(JournalNote {
+            seqnr,
+            note,
+            velocity,
+            on,
+        });
+        if self.notes.len() > MAX_JOURNAL_NOTES {
+            self.notes.remove(0);
+        }
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct RtpPeer {
     status: Status,
@@ -82,7 +204,7 @@ pub(crate) struct RtpPeer {
     remote_ssid: u32,
     remote_name: String,
 
-    // This is as we send, and if now aknowledged, we can resend, maybe with journal TODO
+    // This is as we send, and if not aknowledged, we can resend via the recovery journal
     sequence_nr: u16,
     sequence_ack: u16,
     // This is the last remote as seen, to know if we missed something
@@ -90,6 +212,9 @@ pub(crate) struct RtpPeer {
     timestamp_start: Instant,
     latency: u64,
 
+    // Rolling per-channel recovery-journal snapshot for what we have sent, see ChannelJournal.
+    send_journal: [ChannelJournal; MAX_MIDI_CHANNELS],
+
     // Part of the struct, to prevent mallocs at return.
     // No mem management needed for RtpPeer.
     buffer: [u8; 1500],
@@ -109,6 +234,7 @@ impl RtpPeer {
             remote_sequence_nr: None,
             timestamp_start: Instant::now(),
             latency: 0,
+            send_journal: std::array::from_fn(|_| ChannelJournal::default()),
             buffer: [0; 1500],
         }
     }
@@ -358,16 +484,18 @@ impl RtpPeer {
         let remote_sequence_nr = cursor.read_u16::<BigEndian>().unwrap();
 
         debug!("Sequence nr: {}", remote_sequence_nr);
+        let mut lost_packet = false;
         if let Some(current_sequence_nr) = self.remote_sequence_nr {
             // Warparound || next in seq.
             if (remote_sequence_nr == 0 && current_sequence_nr != 0xFFFF)
-                || (current_sequence_nr != remote_sequence_nr - 1)
+                || (current_sequence_nr != remote_sequence_nr.wrapping_sub(1))
             {
                 warn!(
-                    "Lost packet! prev sequence: {}, current {}. No journal, so something has been lost.", 
-                    current_sequence_nr, 
+                    "Lost packet! prev sequence: {}, current {}. Will try to recover from the journal.",
+                    current_sequence_nr,
                     remote_sequence_nr
                 );
+                lost_packet = true;
             }
         }
         self.remote_sequence_nr = Some(remote_sequence_nr);
@@ -381,20 +509,423 @@ impl RtpPeer {
             );
             return Response::Disconnect(DisconnectReason::BadPeer);
         }
-        let headers_len: usize = cursor.read_u8().unwrap() as usize;
-        if headers_len > 15 {
+
+        let command_header = cursor.read_u8().unwrap();
+        let has_long_len = command_header & 0b1000_0000 != 0; // B
+        let has_journal = command_header & 0b0100_0000 != 0; // J
+        let has_unimplemented_flags = command_header & 0b0011_0000 != 0; // Z, P: not implemented
+        if has_unimplemented_flags {
             error!(
-                "Not implemented non midi packets and length > 15 bytes (header value {:02X})",
-                headers_len
+                "Not implemented first-delta-time or phantom command lists (header value {:02X})",
+                command_header
             );
             return Response::Disconnect(DisconnectReason::BadPacket);
         }
-        if data.len() < 13 + headers_len {
+        let headers_len: usize = if has_long_len {
+            if (cursor.position() as usize) >= data.len() {
+                error!("Packet promised a long command list but is missing the length byte");
+                return Response::Disconnect(DisconnectReason::BadPacket);
+            }
+            let low_byte = cursor.read_u8().unwrap() as usize;
+            (((command_header & 0x0F) as usize) << 8) | low_byte
+        } else {
+            (command_header & 0x0F) as usize
+        };
+        let commands_start = cursor.position() as usize;
+        if data.len() < commands_start + headers_len {
             error!("Packet promised more data than currently has");
             return Response::Disconnect(DisconnectReason::BadPacket);
         }
-        cursor.read(&mut self.buffer[0..headers_len]).unwrap();
-        Response::MidiData(&self.buffer[0..headers_len])
+        if headers_len > self.buffer.len() {
+            error!("Command list ({} bytes) does not fit in the buffer", headers_len);
+            return Response::Disconnect(DisconnectReason::BadPacket);
+        }
+        let commands_end = commands_start + headers_len;
+
+        // If we lost a packet and the sender attached a journal, replay what we missed
+        // (note-offs, controller changes, ...) before this packet's own commands, so nothing
+        // is stuck or stale.
+        let mut recovered_len = 0;
+        if lost_packet {
+            if has_journal {
+                recovered_len = self.recover_from_journal(&data[commands_end..]);
+            } else {
+                warn!("No journal, so something has been lost.");
+            }
+        }
+
+        if recovered_len + headers_len > self.buffer.len() {
+            error!("Recovered journal data left no room for the packet's own commands");
+            recovered_len = self.buffer.len() - headers_len;
+        }
+        self.buffer[recovered_len..recovered_len + headers_len]
+            .copy_from_slice(&data[commands_start..commands_end]);
+        Response::MidiData(&self.buffer[0..recovered_len + headers_len])
+    }
+
+    // Encodes `commands` (a concatenation of raw MIDI channel-voice messages for `channel_nr`)
+    // into an outgoing RTP-MIDI packet and, when it fits, attaches a recovery journal (see the
+    // module note above `CHAPTER_PROGRAM` for the wire format) so a single lost datagram does
+    // not leave the receiver with stuck notes or stale controller values.
+    pub(crate) fn send_midi(&mut self, channel_nr: u8, commands: &[u8]) -> Response {
+        self.sequence_nr = self.sequence_nr.wrapping_add(1);
+        self.update_send_journal(channel_nr, commands);
+        let timestamp = self.get_current_timestamp();
+
+        let command_header_len = if commands.len() > 15 { 2 } else { 1 };
+        if 12 + command_header_len + commands.len() > self.buffer.len() {
+            error!(
+                "MIDI command list ({} bytes) does not fit in a single packet, dropping it",
+                commands.len()
+            );
+            return Response::DoNothing;
+        }
+        let journal_len: usize = 5
+            + self
+                .send_journal
+                .iter()
+                .map(ChannelJournal::encoded_len)
+                .sum::<usize>();
+        let fits = 12 + command_header_len + commands.len() + journal_len <= self.buffer.len();
+        let has_journal = fits && self.send_journal.iter().any(|journal| !journal.is_empty());
+        if !fits {
+            warn!(
+                "Recovery journal ({} bytes) does not fit alongside this packet, sending without it",
+                journal_len
+            );
+        }
+
+        let len = {
+            let mut cursor = Cursor::new(&mut self.buffer[..]);
+            cursor.write_u8(0b1000_0000).unwrap();
+            cursor.write_u8(0b0110_0001).unwrap();
+            cursor.write_u16::<BigEndian>(self.sequence_nr).unwrap();
+            cursor
+                .write_u32::<BigEndian>((timestamp / 100) as u32)
+                .unwrap();
+            cursor.write_u32::<BigEndian>(self.local_ssid).unwrap();
+
+            let mut command_header = if has_journal { 0b0100_0000u8 } else { 0 };
+            if commands.len() > 15 {
+                command_header |= 0b1000_0000 | ((commands.len() >> 8) as u8 & 0x0F);
+                cursor.write_u8(command_header).unwrap();
+                cursor.write_u8((commands.len() & 0xFF) as u8).unwrap();
+            } else {
+                command_header |= commands.len() as u8;
+                cursor.write_u8(command_header).unwrap();
+            }
+            cursor.write(commands).unwrap();
+
+            if has_journal {
+                Self::write_recovery_journal(&self.send_journal, self.sequence_ack, &mut cursor);
+            }
+            cursor.position() as usize
+        };
+
+        Response::NetworkMidiData(&self.buffer[0..len])
+    }
+
+    // The peer has acknowledged everything up to and including `acked_seqnr`, so we no longer
+    // need to be able to recover notes from before that point.
+    pub(crate) fn ack_sequence(&mut self, acked_seqnr: u16) {
+        self.sequence_ack = acked_seqnr;
+        for journal in self.send_journal.iter_mut() {
+            journal.forget_acked_notes(acked_seqnr);
+        }
+    }
+
+    // Updates the rolling per-channel snapshot with the state implied by `commands`, so the
+    // next packet we send can carry a journal that lets the receiver recover them if lost.
+    //
+    // `commands` may use running status (repeated channel-voice messages omitting the status
+    // byte), which is common in real MIDI streams, so we track the last seen status byte
+    // instead of requiring every message to restate it.
+    fn update_send_journal(&mut self, channel_nr: u8, commands: &[u8]) {
+        let seqnr = self.sequence_nr;
+        let journal = &mut self.send_journal[(channel_nr & 0x0F) as usize];
+        let mut i = 0;
+        let mut running_status: Option<u8> = None;
+        while i < commands.len() {
+            let status = if commands[i] & 0x80 != 0 {
+                let status = commands[i];
+                i += 1;
+                running_status = Some(status);
+                status
+            } else if let Some(status) = running_status {
+                status
+            } else {
+                warn!(
+                    "Command list has a data byte ({:#04X}) with no preceding status byte, stopping journal update",
+                    commands[i]
+                );
+                break;
+            };
+
+            match status & 0xF0 {
+                0x80 if i + 1 < commands.len() => {
+                    journal.push_note(seqnr, commands[i], commands[i + 1], false);
+                    i += 2;
+                }
+                0x90 if i + 1 < commands.len() => {
+                    let velocity = commands[i + 1];
+                    journal.push_note(seqnr, commands[i], velocity, velocity != 0);
+                    i += 2;
+                }
+                0xB0 if i + 1 < commands.len() => {
+                    journal.set_controller(commands[i], commands[i + 1]);
+                    i += 2;
+                }
+                0xC0 if i < commands.len() => {
+                    journal.program = Some(commands[i]);
+                    i += 1;
+                }
+                0xD0 if i < commands.len() => {
+                    journal.pressure = Some(commands[i]);
+                    i += 1;
+                }
+                0xE0 if i + 1 < commands.len() => {
+                    // Wire order is LSB, MSB.
+                    journal.pitch_wheel = Some((commands[i + 1], commands[i]));
+                    i += 2;
+                }
+                0xF0..=0xFF => {
+                    // System messages are not channel-voice messages and have no journalable
+                    // state; we just can not keep scanning past one without parsing its body.
+                    warn!(
+                        "Can not journal system message {:#04X}, stopping journal update",
+                        status
+                    );
+                    break;
+                }
+                _ => {
+                    warn!(
+                        "Truncated MIDI command (status {:#04X}), stopping journal update",
+                        status
+                    );
+                    break;
+                }
+            }
+        }
+    }
+
+    // Writes the 5-byte journal header (flags, totlen, checkpoint seqno) followed by one
+    // channel-journal section per non-empty channel.
+    fn write_recovery_journal(
+        channels: &[ChannelJournal; MAX_MIDI_CHANNELS],
+        sequence_ack: u16,
+        cursor: &mut Cursor<&mut [u8]>,
+    ) {
+        let header_pos = cursor.position();
+        cursor.write_u8(0).unwrap();
+        cursor.write_u8(0).unwrap();
+        cursor.write_u8(0).unwrap();
+        cursor.write_u16::<BigEndian>(sequence_ack).unwrap();
+
+        for (channel_nr, journal) in channels.iter().enumerate() {
+            if journal.is_empty() {
+                continue;
+            }
+            let chapters = journal.chapter_mask();
+            let channel_header_pos = cursor.position();
+            cursor.write_u8(((channel_nr as u8) << 4) & 0xF0).unwrap();
+            cursor.write_u8(chapters).unwrap();
+            cursor.write_u16::<BigEndian>(0).unwrap(); // placeholder, filled below
+
+            let content_start = cursor.position();
+            if chapters & CHAPTER_PROGRAM != 0 {
+                cursor.write_u8(journal.program.unwrap()).unwrap();
+            }
+            if chapters & CHAPTER_CONTROL != 0 {
+                cursor.write_u8(journal.controllers.len() as u8).unwrap();
+                for (controller, value) in &journal.controllers {
+                    cursor.write_u8(*controller).unwrap();
+                    cursor.write_u8(*value).unwrap();
+                }
+            }
+            if chapters & CHAPTER_PITCH_WHEEL != 0 {
+                let (msb, lsb) = journal.pitch_wheel.unwrap();
+                cursor.write_u8(msb).unwrap();
+                cursor.write_u8(lsb).unwrap();
+            }
+            if chapters & CHAPTER_NOTE != 0 {
+                let low = journal.notes.first().map_or(sequence_ack, |n| n.seqnr);
+                let high = journal.notes.last().map_or(sequence_ack, |n| n.seqnr);
+                cursor.write_u16::<BigEndian>(low).unwrap();
+                cursor.write_u16::<BigEndian>(high).unwrap();
+                cursor.write_u8(journal.notes.len() as u8).unwrap();
+                for note in &journal.notes {
+                    cursor.write_u16::<BigEndian>(note.seqnr).unwrap();
+                    cursor.write_u8(note.note).unwrap();
+                    cursor
+                        .write_u8(((note.on as u8) << 7) | (note.velocity & 0x7F))
+                        .unwrap();
+                }
+            }
+            if chapters & CHAPTER_CHANNEL_PRESSURE != 0 {
+                cursor.write_u8(journal.pressure.unwrap()).unwrap();
+            }
+
+            let content_len = (cursor.position() - content_start) as u16;
+            let end_pos = cursor.position();
+            cursor
+                .seek(SeekFrom::Start(channel_header_pos + 2))
+                .unwrap();
+            cursor.write_u16::<BigEndian>(content_len).unwrap();
+            cursor.seek(SeekFrom::Start(end_pos)).unwrap();
+        }
+
+        let totlen = (cursor.position() - header_pos) as u16;
+        let end_pos = cursor.position();
+        cursor.seek(SeekFrom::Start(header_pos)).unwrap();
+        // S/Y/A/H all unset: we always send a full (not single-command) journal.
+        cursor.write_u8(((totlen >> 9) & 0x0F) as u8).unwrap();
+        cursor.write_u8(((totlen >> 1) & 0xFF) as u8).unwrap();
+        cursor.write_u8(((totlen & 1) << 7) as u8).unwrap();
+        cursor.seek(SeekFrom::Start(end_pos)).unwrap();
+    }
+
+    // Parses the recovery journal (see the module note above `CHAPTER_PROGRAM` for the wire
+    // format) and synthesizes the MIDI commands the peer says we missed into `self.buffer`.
+    // Returns how many bytes were written.
+    fn recover_from_journal(&mut self, journal: &[u8]) -> usize {
+        if journal.len() < 5 {
+            error!("Recovery journal too small to have a header");
+            return 0;
+        }
+        let totlen = (((journal[0] & 0x0F) as u16) << 9)
+            | ((journal[1] as u16) << 1)
+            | ((journal[2] as u16) >> 7);
+        let checkpoint_seqnr = u16::from_be_bytes(journal[3..5].try_into().unwrap());
+        debug!(
+            "Recovery journal: totlen={}, checkpoint={}",
+            totlen, checkpoint_seqnr
+        );
+
+        let mut written = 0usize;
+        let mut pos = 5usize;
+        while pos + 4 <= journal.len() {
+            let channel_nr = (journal[pos] >> 4) & 0x0F;
+            let chapters = journal[pos + 1];
+            let length = u16::from_be_bytes(journal[pos + 2..pos + 4].try_into().unwrap()) as usize;
+            pos += 4;
+            if pos + length > journal.len() {
+                error!(
+                    "Recovery journal channel {} promises more data than present",
+                    channel_nr
+                );
+                break;
+            }
+            written += self.recover_channel_chapters(
+                channel_nr,
+                chapters,
+                &journal[pos..pos + length],
+                written,
+            );
+            pos += length;
+        }
+        written
+    }
+
+    // Synthesizes MIDI commands from one channel-journal's chapters into self.buffer[offset..],
+    // in the same fixed chapter order they were written (Chapter P, C, W, N, T). Returns how
+    // many bytes were written.
+    fn recover_channel_chapters(
+        &mut self,
+        channel_nr: u8,
+        chapters: u8,
+        data: &[u8],
+        offset: usize,
+    ) -> usize {
+        let status_nibble = channel_nr & 0x0F;
+        let mut pos = 0usize;
+        let mut out = offset;
+
+        if chapters & CHAPTER_PROGRAM != 0 {
+            if pos >= data.len() {
+                error!("Recovery journal channel {}: Chapter P truncated", channel_nr);
+                return out - offset;
+            }
+            out += self.write_recovered(out, &[0xC0 | status_nibble, data[pos]]);
+            pos += 1;
+        }
+        if chapters & CHAPTER_CONTROL != 0 {
+            if pos >= data.len() {
+                error!("Recovery journal channel {}: Chapter C truncated", channel_nr);
+                return out - offset;
+            }
+            let count = data[pos] as usize;
+            pos += 1;
+            for _ in 0..count {
+                if pos + 2 > data.len() {
+                    error!(
+                        "Recovery journal channel {}: Chapter C entry truncated",
+                        channel_nr
+                    );
+                    return out - offset;
+                }
+                out += self.write_recovered(
+                    out,
+                    &[0xB0 | status_nibble, data[pos], data[pos + 1]],
+                );
+                pos += 2;
+            }
+        }
+        if chapters & CHAPTER_PITCH_WHEEL != 0 {
+            if pos + 2 > data.len() {
+                error!("Recovery journal channel {}: Chapter W truncated", channel_nr);
+                return out - offset;
+            }
+            let (msb, lsb) = (data[pos], data[pos + 1]);
+            out += self.write_recovered(out, &[0xE0 | status_nibble, lsb, msb]);
+            pos += 2;
+        }
+        if chapters & CHAPTER_NOTE != 0 {
+            if pos + 5 > data.len() {
+                error!("Recovery journal channel {}: Chapter N truncated", channel_nr);
+                return out - offset;
+            }
+            // Bytes 0..4 are the low/high seqno window, informational only: we just replay
+            // every logged event in order.
+            pos += 4;
+            let count = data[pos] as usize;
+            pos += 1;
+            for _ in 0..count {
+                if pos + 4 > data.len() {
+                    error!(
+                        "Recovery journal channel {}: Chapter N entry truncated",
+                        channel_nr
+                    );
+                    return out - offset;
+                }
+                // seqnr (2 bytes) is informational only.
+                let note = data[pos + 2];
+                let velocity_byte = data[pos + 3];
+                pos += 4;
+                let status = if velocity_byte & 0x80 != 0 { 0x90 } else { 0x80 };
+                out += self.write_recovered(
+                    out,
+                    &[status | status_nibble, note, velocity_byte & 0x7F],
+                );
+            }
+        }
+        if chapters & CHAPTER_CHANNEL_PRESSURE != 0 {
+            if pos >= data.len() {
+                error!("Recovery journal channel {}: Chapter T truncated", channel_nr);
+                return out - offset;
+            }
+            out += self.write_recovered(out, &[0xD0 | status_nibble, data[pos]]);
+        }
+
+        out - offset
+    }
+
+    fn write_recovered(&mut self, at: usize, bytes: &[u8]) -> usize {
+        if at + bytes.len() > self.buffer.len() {
+            error!("Recovered journal data does not fit in the receive buffer, dropping it");
+            return 0;
+        }
+        self.buffer[at..at + bytes.len()].copy_from_slice(bytes);
+        bytes.len()
     }
 }
 
@@ -512,4 +1043,139 @@ mod tests {
         assert_eq!(ret, Response::DoNothing);
         assert_ne!(rtppeer.latency, 0);
     }
+
+    #[test]
+    fn test_send_midi_round_trip_no_loss() {
+        setup_logging();
+        let mut sender = RtpPeer::new("sender".to_string());
+        let mut receiver = RtpPeer::new("receiver".to_string());
+        receiver.remote_ssid = sender.local_ssid;
+
+        let note_on = [0x90, 60, 100];
+        let packet = match sender.send_midi(0, &note_on) {
+            Response::NetworkMidiData(data) => data.to_vec(),
+            other => panic!("Bad type: {:?}", other),
+        };
+
+        let ret = receiver.event(&Event::NetworkMidiData(&packet));
+        assert_eq!(ret, Response::MidiData(&note_on));
+    }
+
+    #[test]
+    fn test_journal_recovers_lost_note_off_and_controller() {
+        setup_logging();
+        let mut sender = RtpPeer::new("sender".to_string());
+        let mut receiver = RtpPeer::new("receiver".to_string());
+        receiver.remote_ssid = sender.local_ssid;
+
+        let channel = 0u8;
+        let note_on = [0x90, 60, 100];
+        let note_off = [0x80, 60, 0];
+        let control_change = [0xB0, 7, 100];
+
+        // Packet 1 arrives fine, and the receiver acks it, so the note on is not journaled
+        // again later.
+        let packet1 = match sender.send_midi(channel, &note_on) {
+            Response::NetworkMidiData(data) => data.to_vec(),
+            other => panic!("Bad type: {:?}", other),
+        };
+        assert_eq!(
+            receiver.event(&Event::NetworkMidiData(&packet1)),
+            Response::MidiData(&note_on)
+        );
+        sender.ack_sequence(sender.sequence_nr);
+
+        // Packet 2 (the note off) gets lost: we never deliver it to the receiver.
+        let _lost_packet = sender.send_midi(channel, &note_off);
+
+        // Packet 3 carries a journal covering the lost note off, which has not been acked yet,
+        // and the current controller state, which the journal always carries.
+        let packet3 = match sender.send_midi(channel, &control_change) {
+            Response::NetworkMidiData(data) => data.to_vec(),
+            other => panic!("Bad type: {:?}", other),
+        };
+
+        let recovered = match receiver.event(&Event::NetworkMidiData(&packet3)) {
+            Response::MidiData(data) => data.to_vec(),
+            other => panic!("Bad type: {:?}", other),
+        };
+
+        // Recovered chapters (control change, then the lost note off) come first, in chapter
+        // order, followed by packet 3's own command list.
+        let expected = [0xB0, 7, 100, 0x80, 60, 0, 0xB0, 7, 100];
+        assert_eq!(recovered, expected);
+    }
+
+    #[test]
+    fn test_recv_long_length_missing_byte_is_rejected() {
+        setup_logging();
+        let mut receiver = RtpPeer::new("receiver".to_string());
+        receiver.remote_ssid = 0xAABBCCDD;
+
+        // A well-formed 12-byte RTP header followed by a command-list header that claims the
+        // "long length" (B) flag, but the packet ends right there with no low-length byte.
+        let packet: [u8; 13] = [
+            0x80, 0x61, // RTP-MIDI marker
+            0x00, 0x01, // sequence nr
+            0x00, 0x00, 0x00, 0x00, // timestamp
+            0xAA, 0xBB, 0xCC, 0xDD, // SSID
+            0b1000_0001, // command header: B set, no low-length byte follows
+        ];
+
+        let ret = receiver.event(&Event::NetworkMidiData(&packet));
+        assert_eq!(ret, Response::Disconnect(crate::rtppeer::DisconnectReason::BadPacket));
+    }
+
+    #[test]
+    fn test_send_midi_rejects_commands_too_large_for_a_packet() {
+        setup_logging();
+        let mut sender = RtpPeer::new("sender".to_string());
+
+        // Larger than the 1500-byte send buffer can hold alongside the RTP/command headers.
+        let oversized_commands = vec![0x90u8; 1500];
+        let ret = sender.send_midi(0, &oversized_commands);
+        assert_eq!(ret, Response::DoNothing);
+    }
+
+    #[test]
+    fn test_update_send_journal_follows_running_status() {
+        setup_logging();
+        let mut sender = RtpPeer::new("sender".to_string());
+        let mut receiver = RtpPeer::new("receiver".to_string());
+        receiver.remote_ssid = sender.local_ssid;
+
+        let channel = 0u8;
+        // Two control changes sharing one status byte (running status): 0xB0 is only sent once.
+        let running_status_controls = [0xB0, 1, 10, 2, 20];
+
+        let packet1 = match sender.send_midi(channel, &running_status_controls) {
+            Response::NetworkMidiData(data) => data.to_vec(),
+            other => panic!("Bad type: {:?}", other),
+        };
+        assert_eq!(
+            receiver.event(&Event::NetworkMidiData(&packet1)),
+            Response::MidiData(&running_status_controls)
+        );
+
+        // Packet 2 is lost, so both running-status controller changes must be recoverable from
+        // the journal packet 3 carries.
+        let _lost_packet = sender.send_midi(channel, &[0x90, 64, 90]);
+        let channel_pressure = [0xD0, 55];
+        let packet3 = match sender.send_midi(channel, &channel_pressure) {
+            Response::NetworkMidiData(data) => data.to_vec(),
+            other => panic!("Bad type: {:?}", other),
+        };
+
+        let recovered = match receiver.event(&Event::NetworkMidiData(&packet3)) {
+            Response::MidiData(data) => data.to_vec(),
+            other => panic!("Bad type: {:?}", other),
+        };
+
+        // Both controllers recognized despite running status, then the lost note on, then the
+        // current channel-pressure state (which also appears as packet 3's own command list).
+        let expected = [
+            0xB0, 1, 10, 0xB0, 2, 20, 0x90, 64, 90, 0xD0, 55, 0xD0, 55,
+        ];
+        assert_eq!(recovered, expected);
+    }
 }
\ No newline at end of file